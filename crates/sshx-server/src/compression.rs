@@ -0,0 +1,161 @@
+//! Transparent response compression for the static frontend.
+//!
+//! Wraps the plain HTTP branch of the hybrid service with a
+//! [`tower_http::compression::CompressionLayer`] that negotiates gzip or
+//! brotli against the client's `Accept-Encoding` header, but only for
+//! responses whose `Content-Type` is in a configurable allowlist. This is
+//! layered onto the HTTP branch alone: it must never see the gRPC branch
+//! (which carries `content-type: application/grpc`) or WebSocket upgrade
+//! responses, since compressing either would break the protocol.
+
+use axum::http::Response;
+use tower_http::compression::{
+    predicate::{NotForContentType, Predicate},
+    CompressionLayer,
+};
+
+/// MIME types that should have their response bodies compressed.
+///
+/// The defaults cover the text-based assets in `dist/`; binary formats like
+/// images and fonts are already compressed and are skipped by default.
+const DEFAULT_COMPRESSIBLE_TYPES: &[&str] = &[
+    "text/html",
+    "text/css",
+    "text/plain",
+    "text/javascript",
+    "application/javascript",
+    "application/json",
+    "image/svg+xml",
+];
+
+/// Which response `Content-Type`s are eligible for compression, passed to
+/// [`crate::make_server`] and [`crate::tls::make_server_tls`] alongside
+/// `custom_headers`/`redirect`.
+///
+/// An empty `mime_types` list disables compression entirely, since nothing
+/// will ever match the allowlist.
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// MIME type essences (`type/subtype`, e.g. `text/html`) eligible for
+    /// compression; see [`mime_essence`] for how responses are matched
+    /// against this list.
+    pub mime_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    /// Use [`DEFAULT_COMPRESSIBLE_TYPES`].
+    fn default() -> Self {
+        Self {
+            mime_types: DEFAULT_COMPRESSIBLE_TYPES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Build the [`CompressionLayer`] applied to the plain HTTP branch of
+    /// the hybrid service, restricted to [`Self::mime_types`].
+    pub fn layer(&self) -> CompressionLayer<impl Predicate> {
+        CompressionLayer::new()
+            .gzip(true)
+            .br(true)
+            .deflate(false)
+            .zstd(false)
+            .compress_when(MimeAllowlist::new(&self.mime_types))
+    }
+}
+
+/// A [`Predicate`] that only allows compression of responses whose
+/// `Content-Type` matches one of a configured set of MIME types, layered on
+/// top of [`tower_http`]'s built-in exclusion of `application/grpc` so the
+/// gRPC branch is never touched even if it were accidentally wrapped.
+///
+/// Matching compares only the MIME essence (the `type/subtype`, lowercased),
+/// ignoring parameters like `; charset=utf-8`, since responders such as
+/// axum's `Html` attach those and an exact-string match would otherwise miss
+/// every response it was meant to catch.
+#[derive(Clone)]
+struct MimeAllowlist {
+    mime_types: Vec<String>,
+    not_grpc: NotForContentType,
+}
+
+impl MimeAllowlist {
+    fn new(mime_types: &[String]) -> Self {
+        Self {
+            mime_types: mime_types.iter().map(|s| mime_essence(s)).collect(),
+            not_grpc: NotForContentType::GRPC,
+        }
+    }
+}
+
+impl Predicate for MimeAllowlist {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: axum::body::HttpBody,
+    {
+        if !self.not_grpc.should_compress(response) {
+            return false;
+        }
+        match response.headers().get(axum::http::header::CONTENT_TYPE) {
+            Some(content_type) => content_type
+                .to_str()
+                .map(|s| self.mime_types.contains(&mime_essence(s)))
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+}
+
+/// Extract the `type/subtype` portion of a `Content-Type` value, dropping
+/// any `; parameter=value` suffix and normalizing case.
+fn mime_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or(content_type)
+        .trim()
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header::CONTENT_TYPE;
+
+    fn response_with(content_type: Option<&str>) -> Response<hyper::Body> {
+        let mut builder = Response::builder();
+        if let Some(content_type) = content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+        builder.body(hyper::Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn mime_essence_strips_parameters_and_lowercases() {
+        assert_eq!(mime_essence("text/html; charset=utf-8"), "text/html");
+        assert_eq!(mime_essence("TEXT/HTML"), "text/html");
+        assert_eq!(mime_essence("application/json"), "application/json");
+    }
+
+    #[test]
+    fn allowlist_matches_essence_ignoring_parameters() {
+        let allowlist = MimeAllowlist::new(&["text/html".to_string()]);
+        assert!(allowlist.should_compress(&response_with(Some("text/html; charset=utf-8"))));
+        assert!(!allowlist.should_compress(&response_with(Some("text/plain"))));
+    }
+
+    #[test]
+    fn allowlist_rejects_response_with_no_content_type() {
+        let allowlist = MimeAllowlist::new(&["text/html".to_string()]);
+        assert!(!allowlist.should_compress(&response_with(None)));
+    }
+
+    #[test]
+    fn allowlist_excludes_grpc_even_if_allowlisted() {
+        let allowlist = MimeAllowlist::new(&["application/grpc".to_string()]);
+        assert!(!allowlist.should_compress(&response_with(Some("application/grpc"))));
+    }
+}
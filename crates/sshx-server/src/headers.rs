@@ -0,0 +1,208 @@
+//! Configurable custom and security response headers.
+//!
+//! Lets operators attach an ordered list of headers to every HTTP response
+//! served by [`web::app`](crate::web::app), for example
+//! `Strict-Transport-Security` or `Content-Security-Policy`. This is plumbed
+//! in as a `tower` layer on the HTTP branch only, so it never touches the
+//! gRPC branch or the plaintext `tls_redirect_service` responses.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::http::{HeaderName, HeaderValue, Request, Response};
+use tower::{Layer, Service};
+
+/// An ordered list of header name/value pairs merged onto every response.
+pub type HeaderList = Vec<(HeaderName, HeaderValue)>;
+
+/// Security-hardening headers an operator can opt into for a publicly
+/// exposed terminal-sharing server, plus any number of fully custom ones.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityHeadersConfig {
+    /// `Strict-Transport-Security`, e.g. `max-age=63072000; includeSubDomains`.
+    ///
+    /// Should only be enabled on a listener that is actually served over
+    /// HTTPS (either natively or behind a TLS-terminating proxy).
+    pub hsts: Option<HeaderValue>,
+    /// `X-Frame-Options`, e.g. `DENY` or `SAMEORIGIN`, to control whether
+    /// the app may be embedded in a frame on older browsers that don't
+    /// support CSP's `frame-ancestors`.
+    pub x_frame_options: Option<HeaderValue>,
+    /// `Content-Security-Policy`, e.g. `frame-ancestors 'self'`. Uses
+    /// different syntax from `x_frame_options` above, so the two are kept
+    /// as separate fields rather than derived from one value.
+    pub content_security_policy: Option<HeaderValue>,
+    /// Whether to send `X-Content-Type-Options: nosniff`.
+    pub content_type_options_nosniff: bool,
+    /// `Referrer-Policy`, e.g. `no-referrer` or `same-origin`.
+    pub referrer_policy: Option<HeaderValue>,
+    /// Additional headers applied after the ones above.
+    pub custom: HeaderList,
+}
+
+impl SecurityHeadersConfig {
+    /// Flatten this configuration into the ordered header list applied by
+    /// [`SetHeadersLayer`].
+    pub fn into_header_list(self) -> HeaderList {
+        let mut headers = HeaderList::new();
+        if let Some(value) = self.hsts {
+            headers.push((axum::http::header::STRICT_TRANSPORT_SECURITY, value));
+        }
+        if let Some(value) = self.x_frame_options {
+            headers.push((HeaderName::from_static("x-frame-options"), value));
+        }
+        if let Some(value) = self.content_security_policy {
+            headers.push((HeaderName::from_static("content-security-policy"), value));
+        }
+        if self.content_type_options_nosniff {
+            headers.push((
+                axum::http::header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ));
+        }
+        if let Some(value) = self.referrer_policy {
+            headers.push((axum::http::header::REFERRER_POLICY, value));
+        }
+        headers.extend(self.custom);
+        headers
+    }
+}
+
+/// A [`tower::Layer`] that merges a fixed set of headers onto every response,
+/// without overwriting a header the inner service already set.
+#[derive(Clone)]
+pub struct SetHeadersLayer {
+    headers: Arc<HeaderList>,
+}
+
+impl SetHeadersLayer {
+    /// Create a layer that applies `headers` to every response, in order.
+    pub fn new(headers: HeaderList) -> Self {
+        Self {
+            headers: Arc::new(headers),
+        }
+    }
+}
+
+impl<S> Layer<S> for SetHeadersLayer {
+    type Service = SetHeaders<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetHeaders {
+            inner,
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+/// The [`tower::Service`] produced by [`SetHeadersLayer`].
+#[derive(Clone)]
+pub struct SetHeaders<S> {
+    inner: S,
+    headers: Arc<HeaderList>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let fut = self.inner.call(req);
+        let headers = self.headers.clone();
+        Box::pin(async move {
+            let mut res = fut.await?;
+            for (name, value) in headers.iter() {
+                res.headers_mut()
+                    .entry(name)
+                    .or_insert_with(|| value.clone());
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::{service_fn, ServiceExt};
+
+    #[test]
+    fn into_header_list_orders_and_omits_unset_fields() {
+        let headers = SecurityHeadersConfig {
+            hsts: Some(HeaderValue::from_static("max-age=1")),
+            x_frame_options: Some(HeaderValue::from_static("DENY")),
+            content_security_policy: Some(HeaderValue::from_static("frame-ancestors 'self'")),
+            content_type_options_nosniff: true,
+            referrer_policy: Some(HeaderValue::from_static("no-referrer")),
+            custom: vec![(
+                HeaderName::from_static("x-custom"),
+                HeaderValue::from_static("1"),
+            )],
+        }
+        .into_header_list();
+
+        let names: Vec<_> = headers.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "strict-transport-security",
+                "x-frame-options",
+                "content-security-policy",
+                "x-content-type-options",
+                "referrer-policy",
+                "x-custom",
+            ]
+        );
+    }
+
+    #[test]
+    fn into_header_list_is_empty_by_default() {
+        assert!(SecurityHeadersConfig::default().into_header_list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn set_headers_does_not_clobber_a_header_the_inner_service_already_set() {
+        let layer = SetHeadersLayer::new(vec![(
+            HeaderName::from_static("x-frame-options"),
+            HeaderValue::from_static("DENY"),
+        )]);
+        let inner = service_fn(|_: Request<()>| async {
+            Ok::<_, std::convert::Infallible>(
+                Response::builder()
+                    .header("x-frame-options", "SAMEORIGIN")
+                    .body(())
+                    .unwrap(),
+            )
+        });
+        let res = layer.layer(inner).oneshot(Request::new(())).await.unwrap();
+        assert_eq!(res.headers().get("x-frame-options").unwrap(), "SAMEORIGIN");
+    }
+
+    #[tokio::test]
+    async fn set_headers_applies_a_header_the_inner_service_did_not_set() {
+        let layer = SetHeadersLayer::new(vec![(
+            HeaderName::from_static("x-content-type-options"),
+            HeaderValue::from_static("nosniff"),
+        )]);
+        let inner =
+            service_fn(|_: Request<()>| async { Ok::<_, std::convert::Infallible>(Response::new(())) });
+        let res = layer.layer(inner).oneshot(Request::new(())).await.unwrap();
+        assert_eq!(
+            res.headers().get("x-content-type-options").unwrap(),
+            "nosniff"
+        );
+    }
+}
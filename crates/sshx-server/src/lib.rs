@@ -15,7 +15,7 @@
 use std::{error::Error as StdError, future::Future, net::SocketAddr};
 
 use anyhow::{anyhow, Result};
-use axum::{body::HttpBody, http::uri::Scheme};
+use axum::body::HttpBody;
 use grpc::GrpcServer;
 use hyper::{
     header::{CONTENT_TYPE, HOST},
@@ -29,15 +29,34 @@ use tower::{service_fn, steer::Steer, ServiceBuilder, ServiceExt};
 use tower_http::{services::Redirect, trace::TraceLayer};
 use tracing::info;
 
-use crate::session::SessionStore;
+use crate::{
+    compression::CompressionConfig, headers::HeaderList, redirect::RedirectConfig,
+    session::SessionStore,
+};
 
+pub mod compression;
 pub mod grpc;
+pub mod headers;
+pub mod redirect;
 pub mod session;
+pub mod tls;
 pub mod web;
 
 /// Make the combined HTTP/gRPC application server, on a given listener.
+///
+/// `custom_headers` is an ordered list of headers, such as those built by
+/// [`headers::SecurityHeadersConfig::into_header_list`], merged onto every
+/// HTTP response without overwriting one the handler already set. It is
+/// applied only to the HTTP branch, so it never reaches the gRPC branch or
+/// the plaintext redirect responses. `redirect` controls whether and how
+/// plaintext requests are redirected to HTTPS; see [`RedirectConfig`].
+/// `compression` controls which response `Content-Type`s are eligible for
+/// gzip/brotli compression; see [`CompressionConfig`].
 pub async fn make_server(
     builder: Builder<AddrIncoming>,
+    custom_headers: HeaderList,
+    redirect: RedirectConfig,
+    compression: CompressionConfig,
     signal: impl Future<Output = ()>,
 ) -> Result<()> {
     type BoxError = Box<dyn StdError + Send + Sync>;
@@ -45,6 +64,8 @@ pub async fn make_server(
     let store = SessionStore::default();
 
     let http_service = web::app(store.clone())
+        .layer(headers::SetHeadersLayer::new(custom_headers))
+        .layer(compression.layer())
         .layer(TraceLayer::new_for_http())
         .map_response(|r| r.map(|b| b.map_err(BoxError::from).boxed_unsync()))
         .map_err(BoxError::from)
@@ -65,33 +86,51 @@ pub async fn make_server(
         .map_response(|r| r.map(|b| b.map_err(BoxError::from).boxed_unsync()))
         .boxed_clone();
 
-    let tls_redirect_service = service_fn(|req: Request<Body>| async {
-        let uri = req.uri();
-        info!(method = ?req.method(), %uri, "redirecting to https");
-        let mut parts = uri.clone().into_parts();
-        parts.scheme = Some(Scheme::HTTPS);
-        parts.authority = Some(
-            req.headers()
-                .get(HOST)
-                .ok_or_else(|| anyhow!("tls redirect missing host"))?
-                .to_str()?
-                .parse()?,
-        );
-        Ok(Redirect::permanent(parts.try_into()?).oneshot(req).await?)
+    let redirect_for_steer = redirect.clone();
+    let tls_redirect_service = service_fn(move |req: Request<Body>| {
+        let redirect = redirect.clone();
+        async move {
+            let uri = req.uri();
+            info!(method = ?req.method(), %uri, "redirecting to https");
+            let mut parts = uri.clone().into_parts();
+            parts.scheme = Some(redirect.target_scheme());
+            parts.authority = Some(redirect.target_authority(
+                req.headers()
+                    .get(HOST)
+                    .ok_or_else(|| anyhow!("tls redirect missing host"))?,
+            )?);
+            let target = parts.try_into()?;
+            let redirect_svc = if redirect.permanent {
+                Redirect::permanent(target)
+            } else {
+                Redirect::temporary(target)
+            };
+            Ok(redirect_svc.oneshot(req).await?)
+        }
     })
     .boxed_clone();
 
     let svc = Steer::new(
         [http_service, grpc_service, tls_redirect_service],
-        |req: &Request<Body>, _services: &[_]| {
+        move |req: &Request<Body>, _services: &[_]| {
             let headers = req.headers();
-            match (headers.get("x-forwarded-proto"), headers.get(CONTENT_TYPE)) {
-                // Redirect proxied HTTP to HTTPS, see here for details:
-                // https://fly.io/blog/always-be-connecting-with-https/
-                (Some(proto), _) if proto == "http" => 2,
-                (_, Some(content)) if content == "application/grpc" => 1,
-                _ => 0,
+            // A gRPC request is always routed to the gRPC branch, regardless
+            // of scheme, since there is no meaningful "redirect" for it.
+            if matches!(headers.get(CONTENT_TYPE), Some(content) if content == "application/grpc") {
+                return 1;
+            }
+            // Redirect proxied HTTP to HTTPS, see here for details:
+            // https://fly.io/blog/always-be-connecting-with-https/
+            let forwarded_proto = headers.get("x-forwarded-proto");
+            let is_proxied_http = matches!(forwarded_proto, Some(proto) if proto == "http");
+            // Optionally also redirect direct plaintext connections to this
+            // listener, i.e. ones with no `x-forwarded-proto` header at all.
+            let is_direct_plaintext =
+                redirect_for_steer.redirect_direct_plaintext && forwarded_proto.is_none();
+            if redirect_for_steer.enabled && (is_proxied_http || is_direct_plaintext) {
+                return 2;
             }
+            0
         },
     );
     let make_svc = make_service_fn(move |_| {
@@ -108,6 +147,19 @@ pub async fn make_server(
 }
 
 /// Convenience function to call [`make_server`] bound to a TCP address.
-pub async fn make_server_bind(addr: &SocketAddr, signal: impl Future<Output = ()>) -> Result<()> {
-    make_server(Server::try_bind(addr)?, signal).await
+pub async fn make_server_bind(
+    addr: &SocketAddr,
+    custom_headers: HeaderList,
+    redirect: RedirectConfig,
+    compression: CompressionConfig,
+    signal: impl Future<Output = ()>,
+) -> Result<()> {
+    make_server(
+        Server::try_bind(addr)?,
+        custom_headers,
+        redirect,
+        compression,
+        signal,
+    )
+    .await
 }
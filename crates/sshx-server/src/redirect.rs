@@ -0,0 +1,133 @@
+//! Configuration for the HTTP-to-HTTPS redirect.
+//!
+//! The hybrid service in [`crate::make_server`] can redirect plaintext
+//! requests to HTTPS when a fronting proxy reports the original request as
+//! `http` via `x-forwarded-proto`. [`RedirectConfig`] lets operators disable
+//! that behavior, choose between a permanent and temporary redirect, and
+//! override the port used in the redirect target.
+
+use anyhow::{Context, Result};
+use axum::http::{
+    header::HeaderValue,
+    uri::{Authority, Scheme},
+};
+
+/// Controls how (and whether) plaintext HTTP requests are redirected to
+/// HTTPS by [`crate::make_server`].
+#[derive(Debug, Clone)]
+pub struct RedirectConfig {
+    /// Whether to redirect plaintext requests to HTTPS at all. Operators who
+    /// terminate TLS entirely outside of sshx's knowledge, or who
+    /// intentionally serve plain HTTP, should set this to `false`.
+    pub enabled: bool,
+    /// Use a permanent (308) redirect instead of a temporary (307) one.
+    /// Permanent redirects are cached by browsers, which is usually desired
+    /// once a deployment's TLS setup is stable.
+    pub permanent: bool,
+    /// Port to insert into the redirect target's authority, e.g. `8443` for
+    /// a native HTTPS listener on a non-standard port. `None` keeps the
+    /// authority from the client's `Host` header as-is, including any port
+    /// it carries.
+    pub port: Option<u16>,
+    /// Also redirect requests that carry no `x-forwarded-proto` header at
+    /// all, treating them as direct plaintext connections to this listener.
+    ///
+    /// This is an explicit opt-in, off by default: most deployments run
+    /// behind a proxy that either always sets `x-forwarded-proto`, or that
+    /// itself terminates TLS and never forwards plaintext traffic here, so
+    /// requests without the header are usually direct localhost access or
+    /// health checks rather than a real client. Enabling this behind a
+    /// proxy that doesn't set `x-forwarded-proto` causes a redirect loop.
+    pub redirect_direct_plaintext: bool,
+}
+
+impl Default for RedirectConfig {
+    /// Permanently redirect proxied HTTP to HTTPS on the default port,
+    /// matching sshx's historical behavior. Requests without an explicit
+    /// `x-forwarded-proto: http` are served as-is.
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            permanent: true,
+            port: None,
+            redirect_direct_plaintext: false,
+        }
+    }
+}
+
+impl RedirectConfig {
+    /// Build the `https://` target authority for a redirect, from the
+    /// client-supplied `Host` header, applying [`Self::port`] if set.
+    ///
+    /// Parses `host` as a [`Authority`] rather than splitting on `:`, so
+    /// that bracketed IPv6 literals like `[::1]:8080` are handled correctly.
+    /// When [`Self::port`] is `None`, the authority (including whatever
+    /// port, if any, the client sent) is preserved verbatim.
+    pub fn target_authority(&self, host: &HeaderValue) -> Result<Authority> {
+        let host = host.to_str().context("host header is not valid UTF-8")?;
+        let authority: Authority = host.parse().context("invalid host header")?;
+        match self.port {
+            Some(port) => format!("{}:{port}", authority.host())
+                .parse()
+                .context("invalid redirect host/port"),
+            None => Ok(authority),
+        }
+    }
+
+    /// The target scheme for the redirect, always `https`.
+    pub fn target_scheme(&self) -> Scheme {
+        Scheme::HTTPS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(port: Option<u16>) -> RedirectConfig {
+        RedirectConfig {
+            port,
+            ..RedirectConfig::default()
+        }
+    }
+
+    #[test]
+    fn target_authority_overrides_port() {
+        let authority = config(Some(8443))
+            .target_authority(&HeaderValue::from_static("example.com:8080"))
+            .unwrap();
+        assert_eq!(authority, "example.com:8443");
+    }
+
+    #[test]
+    fn target_authority_preserves_client_port_when_unset() {
+        let authority = config(None)
+            .target_authority(&HeaderValue::from_static("example.com:8080"))
+            .unwrap();
+        assert_eq!(authority, "example.com:8080");
+    }
+
+    #[test]
+    fn target_authority_preserves_bare_host_when_unset() {
+        let authority = config(None)
+            .target_authority(&HeaderValue::from_static("example.com"))
+            .unwrap();
+        assert_eq!(authority, "example.com");
+    }
+
+    #[test]
+    fn target_authority_handles_ipv6_literal_with_port_override() {
+        let authority = config(Some(8443))
+            .target_authority(&HeaderValue::from_static("[::1]:8080"))
+            .unwrap();
+        assert_eq!(authority, "[::1]:8443");
+    }
+
+    #[test]
+    fn target_authority_preserves_ipv6_literal_when_unset() {
+        let authority = config(None)
+            .target_authority(&HeaderValue::from_static("[::1]:8080"))
+            .unwrap();
+        assert_eq!(authority, "[::1]:8080");
+    }
+}
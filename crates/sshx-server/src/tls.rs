@@ -0,0 +1,441 @@
+//! Native TLS termination with automatic certificate provisioning.
+//!
+//! This lets sshx terminate HTTPS directly, without requiring an upstream
+//! proxy to handle TLS. Certificates are obtained and kept fresh using the
+//! ACME protocol (see [`acme`]), and are swapped into the live listener with
+//! zero downtime: the old certificate keeps serving traffic until a
+//! replacement has been validated.
+
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use axum::{body::HttpBody, http::header::CONTENT_TYPE};
+use futures_util::{ready, stream::FuturesUnordered, StreamExt};
+use hyper::{
+    server::{
+        accept::Accept,
+        conn::{AddrIncoming, AddrStream},
+        Server,
+    },
+    service::make_service_fn,
+    Body, Request,
+};
+use rcgen::{CertificateParams, CustomExtension, DistinguishedName, PKCS_ECDSA_P384_SHA384};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    ServerConfig,
+};
+use tokio::{fs, sync::watch, time::sleep};
+use tokio_rustls::server::TlsStream;
+use tower::{ServiceBuilder, ServiceExt};
+use tower_http::trace::TraceLayer;
+use tracing::{debug, info, warn};
+
+use crate::{
+    grpc::GrpcServer,
+    session::SessionStore,
+    tls::acme::{tls_alpn01_digest, AcmeClient, ChallengeKind},
+};
+use sshx_core::proto::{sshx_service_server::SshxServiceServer, FILE_DESCRIPTOR_SET};
+use tonic::transport::Server as TonicServer;
+
+pub mod acme;
+
+/// How long before a certificate's expiry to attempt renewal.
+const RENEWAL_MARGIN: Duration = Duration::from_secs(21 * 24 * 60 * 60); // 3 weeks
+
+/// How often to check whether the current certificate needs renewing.
+const RENEWAL_CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60); // 12 hours
+
+/// The ALPN protocol ID a client offers during the TLS handshake to request
+/// the `tls-alpn-01` challenge certificate, per
+/// [RFC 8737](https://www.rfc-editor.org/rfc/rfc8737#section-3).
+const ACME_TLS_ALPN_PROTOCOL: &[u8] = b"acme-tls/1";
+
+/// Configuration for the native HTTPS listener and its certificate lifecycle.
+///
+/// Certificates are proven via the `tls-alpn-01` challenge only: since this
+/// listener only ever speaks TLS, it has no plaintext `:80` path to serve an
+/// `http-01` token on, so [`acme::ChallengeKind::Http01`] cannot be
+/// validated here. `tls-alpn-01` is satisfied entirely within the TLS
+/// handshake via [`CertResolver`], which this listener already terminates.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// Domains the certificate should cover; the first is used as the CN.
+    pub domains: Vec<String>,
+    /// Directory URL of the ACME server, e.g. Let's Encrypt or a local Pebble
+    /// instance used for testing.
+    pub acme_directory_url: String,
+    /// Contact email registered with the ACME account, if any.
+    pub acme_contact_email: Option<String>,
+    /// Directory on disk where the issued certificate and key are cached
+    /// between restarts.
+    pub cache_dir: PathBuf,
+}
+
+/// Holds the self-signed `tls-alpn-01` challenge certificates that are
+/// currently outstanding, keyed by domain name, so [`CertResolver`] can
+/// present one in place of the real certificate when a validation
+/// connection negotiates the `acme-tls/1` ALPN protocol.
+#[derive(Debug, Default)]
+struct AlpnChallengeStore(Mutex<HashMap<String, Arc<CertifiedKey>>>);
+
+impl AlpnChallengeStore {
+    fn insert(&self, domain: String, cert: Arc<CertifiedKey>) {
+        self.0.lock().unwrap().insert(domain, cert);
+    }
+
+    fn remove(&self, domain: &str) {
+        self.0.lock().unwrap().remove(domain);
+    }
+
+    fn get(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        self.0.lock().unwrap().get(domain).cloned()
+    }
+}
+
+/// Resolves the certificate presented to TLS clients.
+///
+/// The certificate is held behind a [`watch::Receiver`] so that the
+/// background renewal task in [`spawn_renewal_task`] can swap it out for a
+/// freshly issued one without interrupting in-flight connections. When a
+/// handshake offers the `acme-tls/1` ALPN protocol, an outstanding
+/// `tls-alpn-01` challenge certificate from `alpn_challenges` is presented
+/// instead, per [RFC 8737](https://www.rfc-editor.org/rfc/rfc8737#section-3).
+#[derive(Debug)]
+struct CertResolver {
+    current: watch::Receiver<Arc<CertifiedKey>>,
+    alpn_challenges: Arc<AlpnChallengeStore>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let wants_alpn_challenge = client_hello
+            .alpn()
+            .into_iter()
+            .flatten()
+            .any(|protocol| protocol == ACME_TLS_ALPN_PROTOCOL);
+        if wants_alpn_challenge {
+            let domain = client_hello.server_name()?;
+            return self.alpn_challenges.get(domain);
+        }
+        Some(self.current.borrow().clone())
+    }
+}
+
+/// Make the combined HTTP/gRPC application server, terminating TLS natively
+/// and keeping its certificate renewed via ACME.
+///
+/// Unlike [`crate::make_server`], this does not need a `tls_redirect_service`
+/// branch: the listener only ever speaks TLS, so there is nothing to
+/// redirect away from.
+pub async fn make_server_tls(
+    incoming: AddrIncoming,
+    config: TlsConfig,
+    custom_headers: crate::headers::HeaderList,
+    compression: crate::compression::CompressionConfig,
+    signal: impl Future<Output = ()>,
+) -> Result<()> {
+    type BoxError = Box<dyn StdError + Send + Sync>;
+
+    fs::create_dir_all(&config.cache_dir).await?;
+
+    let alpn_challenges = Arc::new(AlpnChallengeStore::default());
+    let initial = load_or_issue_certificate(&config, &alpn_challenges).await?;
+    let (tx, rx) = watch::channel(Arc::new(initial));
+    let resolver = Arc::new(CertResolver {
+        current: rx,
+        alpn_challenges: alpn_challenges.clone(),
+    });
+
+    let mut server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    server_config.alpn_protocols = vec![
+        b"h2".to_vec(),
+        b"http/1.1".to_vec(),
+        ACME_TLS_ALPN_PROTOCOL.to_vec(),
+    ];
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+
+    tokio::spawn(spawn_renewal_task(
+        config.clone(),
+        alpn_challenges.clone(),
+        tx,
+    ));
+
+    let store = SessionStore::default();
+
+    let http_service = crate::web::app(store.clone())
+        .layer(crate::headers::SetHeadersLayer::new(custom_headers))
+        .layer(compression.layer())
+        .layer(TraceLayer::new_for_http())
+        .map_response(|r| r.map(|b| b.map_err(BoxError::from).boxed_unsync()))
+        .map_err(BoxError::from)
+        .boxed_clone();
+
+    let grpc_service = TonicServer::builder()
+        .add_service(SshxServiceServer::new(GrpcServer::new(store)))
+        .add_service(
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+                .build()?,
+        )
+        .into_service();
+
+    let grpc_service = ServiceBuilder::new()
+        .layer(TraceLayer::new_for_grpc())
+        .service(grpc_service)
+        .map_response(|r| r.map(|b| b.map_err(BoxError::from).boxed_unsync()))
+        .boxed_clone();
+
+    let svc = tower::steer::Steer::new(
+        [http_service, grpc_service],
+        |req: &Request<Body>, _services: &[_]| {
+            match req.headers().get(CONTENT_TYPE) {
+                Some(content) if content == "application/grpc" => 1,
+                _ => 0,
+            }
+        },
+    );
+    let make_svc = make_service_fn(move |_| {
+        let svc = svc.clone();
+        async { Ok::<_, std::convert::Infallible>(svc) }
+    });
+
+    Server::builder(TlsIncoming::new(incoming, acceptor))
+        .serve(make_svc)
+        .with_graceful_shutdown(signal)
+        .await?;
+
+    Ok(())
+}
+
+/// Convenience wrapper around [`make_server_tls`] that binds to `addr`.
+pub async fn make_server_tls_bind(
+    addr: &std::net::SocketAddr,
+    config: TlsConfig,
+    custom_headers: crate::headers::HeaderList,
+    compression: crate::compression::CompressionConfig,
+    signal: impl Future<Output = ()>,
+) -> Result<()> {
+    make_server_tls(
+        AddrIncoming::bind(addr)?,
+        config,
+        custom_headers,
+        compression,
+        signal,
+    )
+    .await
+}
+
+/// A [`hyper`] `Accept` implementation that wraps a plain TCP listener and
+/// performs the TLS handshake for each incoming connection before it is
+/// handed to the hybrid HTTP/gRPC service.
+struct TlsIncoming {
+    incoming: AddrIncoming,
+    acceptor: tokio_rustls::TlsAcceptor,
+    handshakes: FuturesUnordered<Pin<Box<dyn Future<Output = std::io::Result<TlsStream<AddrStream>>> + Send>>>,
+}
+
+impl TlsIncoming {
+    fn new(incoming: AddrIncoming, acceptor: tokio_rustls::TlsAcceptor) -> Self {
+        Self {
+            incoming,
+            acceptor,
+            handshakes: FuturesUnordered::new(),
+        }
+    }
+}
+
+impl Accept for TlsIncoming {
+    type Conn = TlsStream<AddrStream>;
+    type Error = std::io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<std::io::Result<Self::Conn>>> {
+        loop {
+            if let Poll::Ready(Some(result)) = self.handshakes.poll_next_unpin(cx) {
+                match result {
+                    Ok(stream) => return Poll::Ready(Some(Ok(stream))),
+                    Err(err) => {
+                        debug!(?err, "tls handshake failed");
+                        continue;
+                    }
+                }
+            }
+
+            match ready!(Pin::new(&mut self.incoming).poll_accept(cx)) {
+                Some(Ok(stream)) => {
+                    let acceptor = self.acceptor.clone();
+                    self.handshakes
+                        .push(Box::pin(async move { acceptor.accept(stream).await }));
+                }
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Periodically check the current certificate's expiry and request a
+/// replacement from the ACME server well before it lapses, publishing the
+/// new certificate over `tx` once issued.
+async fn spawn_renewal_task(
+    config: TlsConfig,
+    alpn_challenges: Arc<AlpnChallengeStore>,
+    tx: watch::Sender<Arc<CertifiedKey>>,
+) {
+    loop {
+        sleep(RENEWAL_CHECK_INTERVAL).await;
+
+        if !needs_renewal(&config).await.unwrap_or(true) {
+            continue;
+        }
+
+        info!(domains = ?config.domains, "renewing TLS certificate");
+        match issue_certificate(&config, &alpn_challenges).await {
+            Ok(cert) => {
+                if tx.send(Arc::new(cert)).is_err() {
+                    // All receivers dropped, meaning the server has shut down.
+                    return;
+                }
+            }
+            Err(err) => warn!(?err, "failed to renew TLS certificate, will retry later"),
+        }
+    }
+}
+
+/// Load a cached certificate from disk if one is present and not close to
+/// expiring, otherwise request a new one.
+async fn load_or_issue_certificate(
+    config: &TlsConfig,
+    alpn_challenges: &Arc<AlpnChallengeStore>,
+) -> Result<CertifiedKey> {
+    if fs::metadata(cert_path(config)).await.is_ok() && !needs_renewal(config).await.unwrap_or(true)
+    {
+        if let Ok(cert) = read_certified_key(config).await {
+            info!(domains = ?config.domains, "loaded cached TLS certificate");
+            return Ok(cert);
+        }
+    }
+    issue_certificate(config, alpn_challenges).await
+}
+
+/// Request a fresh certificate from the ACME server and persist it to disk,
+/// fulfilling the `tls-alpn-01` challenge via `alpn_challenges` (see
+/// [`TlsConfig`] for why this is the only challenge kind this listener
+/// supports).
+async fn issue_certificate(
+    config: &TlsConfig,
+    alpn_challenges: &Arc<AlpnChallengeStore>,
+) -> Result<CertifiedKey> {
+    let client = AcmeClient::new(
+        &config.acme_directory_url,
+        config.acme_contact_email.as_deref(),
+    )
+    .await?;
+
+    let challenges = alpn_challenges.clone();
+    let issued = client
+        .issue_certificate(
+            &config.domains,
+            ChallengeKind::TlsAlpn01,
+            move |domain, challenge| {
+                let cert = build_alpn_challenge_cert(
+                    domain,
+                    &tls_alpn01_digest(&challenge.key_authorization()?),
+                )?;
+                challenges.insert(domain.to_string(), Arc::new(cert));
+                Ok(())
+            },
+        )
+        .await?;
+    for domain in &config.domains {
+        alpn_challenges.remove(domain);
+    }
+
+    fs::write(cert_path(config), &issued.chain_pem).await?;
+    fs::write(key_path(config), &issued.key_pem).await?;
+
+    parse_certified_key(&issued.chain_pem, &issued.key_pem)
+}
+
+/// Build a self-signed `tls-alpn-01` challenge certificate for `domain`,
+/// embedding `digest` in a critical `id-pe-acmeIdentifier` extension, per
+/// [RFC 8737 section 3](https://www.rfc-editor.org/rfc/rfc8737#section-3).
+fn build_alpn_challenge_cert(domain: &str, digest: &[u8; 32]) -> Result<CertifiedKey> {
+    let mut params = CertificateParams::new(vec![domain.to_string()]);
+    params.alg = &PKCS_ECDSA_P384_SHA384;
+    params.distinguished_name = DistinguishedName::new();
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(digest)];
+
+    let cert = rcgen::Certificate::from_params(params)
+        .context("failed to build tls-alpn-01 challenge certificate")?;
+    let cert_der = cert.serialize_der()?;
+    let key_der = cert.serialize_private_key_der();
+
+    let key = sign::any_supported_type(&rustls::PrivateKey(key_der))
+        .context("unsupported tls-alpn-01 challenge key type")?;
+    Ok(CertifiedKey::new(vec![rustls::Certificate(cert_der)], key))
+}
+
+/// Whether the cached certificate is close enough to `not_after` that it
+/// should be renewed now. Returns an error if the cached certificate is
+/// missing or unreadable.
+async fn needs_renewal(config: &TlsConfig) -> Result<bool> {
+    let pem = fs::read(cert_path(config)).await?;
+    let cert = openssl::x509::X509::from_pem(&pem)?;
+    Ok(time_until_expiry(&cert)? < RENEWAL_MARGIN)
+}
+
+async fn read_certified_key(config: &TlsConfig) -> Result<CertifiedKey> {
+    let chain_pem = fs::read(cert_path(config)).await?;
+    let key_pem = fs::read(key_path(config)).await?;
+    parse_certified_key(&chain_pem, &key_pem)
+}
+
+fn parse_certified_key(chain_pem: &[u8], key_pem: &[u8]) -> Result<CertifiedKey> {
+    let chain = rustls_pemfile::certs(&mut &chain_pem[..])
+        .context("failed to parse certificate chain")?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut &key_pem[..])
+        .context("failed to parse private key")?
+        .into_iter()
+        .next()
+        .context("no private key found in cache")?;
+    let key =
+        sign::any_supported_type(&rustls::PrivateKey(key)).context("unsupported private key type")?;
+    Ok(CertifiedKey::new(chain, key))
+}
+
+fn cert_path(config: &TlsConfig) -> PathBuf {
+    config.cache_dir.join("cert.pem")
+}
+
+fn key_path(config: &TlsConfig) -> PathBuf {
+    config.cache_dir.join("key.pem")
+}
+
+/// Time remaining until an X.509 certificate's `not_after` timestamp, or
+/// zero if it has already expired.
+fn time_until_expiry(cert: &openssl::x509::X509) -> Result<Duration> {
+    let now = openssl::asn1::Asn1Time::days_from_now(0)?;
+    let days = now.diff(cert.not_after())?.days.max(0);
+    Ok(Duration::from_secs(days as u64 * 86400))
+}
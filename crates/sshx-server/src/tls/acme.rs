@@ -0,0 +1,175 @@
+//! A minimal ACME client used to provision certificates from Let's Encrypt
+//! (or a local Pebble server for testing) without any manual intervention.
+//!
+//! This implements just enough of the protocol to support the `http-01` and
+//! `tls-alpn-01` challenge types: generating an account key, registering an
+//! account, creating an order for a set of domains, fulfilling whichever
+//! challenge the caller chooses, and finalizing the order into a certificate
+//! chain.
+
+use std::sync::Arc;
+
+use acme2::{
+    AccountBuilder, AuthorizationStatus, Challenge, ChallengeStatus, Csr, DirectoryBuilder,
+    OrderBuilder, OrderStatus,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::X509;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info};
+
+/// The type of challenge used to prove control of a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChallengeKind {
+    /// Serve a token at `/.well-known/acme-challenge/{token}` over HTTP.
+    Http01,
+    /// Present a self-signed certificate during the TLS handshake when the
+    /// client offers the `acme-tls/1` ALPN protocol.
+    TlsAlpn01,
+}
+
+/// A certificate chain and private key returned by a successful order.
+pub struct IssuedCertificate {
+    /// The leaf certificate followed by any intermediates, in PEM form.
+    pub chain_pem: Vec<u8>,
+    /// The private key corresponding to the leaf certificate, in PEM form.
+    pub key_pem: Vec<u8>,
+}
+
+/// A thin wrapper around an ACME account, used to request certificates for
+/// one or more domains.
+pub struct AcmeClient {
+    account: Arc<acme2::Account>,
+}
+
+impl AcmeClient {
+    /// Register a new account with the ACME directory at `directory_url`,
+    /// generating a fresh P-384 account key.
+    pub async fn new(directory_url: &str, contact_email: Option<&str>) -> Result<Self> {
+        let directory = DirectoryBuilder::new(directory_url.to_string())
+            .build()
+            .await
+            .context("failed to fetch ACME directory")?;
+
+        let mut builder = AccountBuilder::new(directory);
+        builder.terms_of_service_agreed(true);
+        if let Some(email) = contact_email {
+            builder.contact(vec![format!("mailto:{email}")]);
+        }
+        builder.private_key(gen_p384_private_key()?);
+
+        let account = builder.build().await.context("failed to register ACME account")?;
+        Ok(Self { account })
+    }
+
+    /// Request a certificate covering `domains`, fulfilling the challenge of
+    /// kind `kind` via the given `respond` callback. For each domain, the
+    /// callback is handed the domain name and the ACME [`Challenge`] to
+    /// fulfill, and must make the response discoverable (e.g. publish the
+    /// `http-01` token, or register a `tls-alpn-01` challenge certificate
+    /// with the TLS resolver) before returning.
+    pub async fn issue_certificate(
+        &self,
+        domains: &[String],
+        kind: ChallengeKind,
+        mut respond: impl FnMut(&str, &Challenge) -> Result<()>,
+    ) -> Result<IssuedCertificate> {
+        if domains.is_empty() {
+            bail!("at least one domain is required to request a certificate");
+        }
+
+        let mut order = OrderBuilder::new(self.account.clone())
+            .add_dns_identifiers(domains.iter().cloned())
+            .build()
+            .await
+            .context("failed to create ACME order")?;
+
+        let authorizations = order.authorizations().await?;
+        for auth in &authorizations {
+            if auth.status == AuthorizationStatus::Valid {
+                continue;
+            }
+            let challenge_type = match kind {
+                ChallengeKind::Http01 => "http-01",
+                ChallengeKind::TlsAlpn01 => "tls-alpn-01",
+            };
+            let challenge = auth
+                .get_challenge(challenge_type)
+                .ok_or_else(|| anyhow!("ACME server did not offer a {challenge_type} challenge"))?;
+
+            respond(&auth.identifier.value, &challenge)?;
+
+            let mut challenge = challenge.validate().await?;
+            for _ in 0..60 {
+                if challenge.status == ChallengeStatus::Valid {
+                    break;
+                }
+                sleep(Duration::from_secs(1)).await;
+                challenge = challenge.poll().await?;
+            }
+            if challenge.status != ChallengeStatus::Valid {
+                bail!("timed out waiting for {challenge_type} challenge to validate");
+            }
+            debug!(domain = ?auth.identifier, "challenge validated");
+        }
+
+        for _ in 0..60 {
+            if order.status == OrderStatus::Ready {
+                break;
+            }
+            sleep(Duration::from_secs(1)).await;
+            order = order.poll().await?;
+        }
+
+        let private_key: PKey<Private> = acme2::gen_rsa_private_key(4096)?;
+        let csr = Csr::Automatic(private_key.clone());
+        order.finalize(csr).await?;
+        for _ in 0..60 {
+            if order.status == OrderStatus::Valid {
+                break;
+            }
+            sleep(Duration::from_secs(1)).await;
+            order = order.poll().await?;
+        }
+        if order.status != OrderStatus::Valid {
+            bail!("timed out waiting for ACME order to finalize");
+        }
+
+        let cert_chain: Vec<X509> = order
+            .certificate()
+            .await?
+            .ok_or_else(|| anyhow!("ACME order finalized without a certificate"))?;
+
+        let mut chain_pem = Vec::new();
+        for cert in &cert_chain {
+            chain_pem.extend(cert.to_pem()?);
+        }
+
+        info!(?domains, "issued new certificate");
+        Ok(IssuedCertificate {
+            chain_pem,
+            key_pem: private_key.private_key_to_pem_pkcs8()?,
+        })
+    }
+}
+
+/// Generate a fresh P-384 (secp384r1) ECDSA private key for ACME account
+/// registration, as recommended by Let's Encrypt over RSA.
+fn gen_p384_private_key() -> Result<PKey<Private>> {
+    let group = EcGroup::from_curve_name(Nid::SECP384R1)?;
+    let ec_key = EcKey::generate(&group)?;
+    Ok(PKey::from_ec_key(ec_key)?)
+}
+
+/// The SHA-256 digest of a `tls-alpn-01` key authorization, as embedded in
+/// the `id-pe-acmeIdentifier` extension of the challenge certificate (see
+/// [RFC 8737 section 3](https://www.rfc-editor.org/rfc/rfc8737#section-3)).
+pub fn tls_alpn01_digest(key_authorization: &str) -> [u8; 32] {
+    use openssl::sha::Sha256;
+    let mut hasher = Sha256::new();
+    hasher.update(key_authorization.as_bytes());
+    hasher.finish()
+}